@@ -0,0 +1,19 @@
+// 编译时写入 git 提交号和目标平台，供 --version 输出
+fn main() {
+    let commit = std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=WEI_DAEMON_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=WEI_DAEMON_TARGET={}", std::env::var("TARGET").unwrap());
+
+    for path in [".git/HEAD", ".git/refs"] {
+        if std::path::Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+}
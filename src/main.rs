@@ -1,8 +1,37 @@
 #[macro_use]
 extern crate wei_log;
 
-#[tokio::main(flavor = "multi_thread", worker_threads = 100)]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+// 守护循环本身是阻塞的，每个程序都放在独立线程里运行，不需要 tokio 运行时
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--version" || arg == "-V") {
+        print_version(args.iter().any(|arg| arg == "--json"));
+        return Ok(());
+    }
+
+    // --config 指定配置文件路径，"-" 表示从标准输入读取
+    let config = arg_value(&args, "--config").unwrap_or_else(|| "./daemon.dat".to_string());
+    // --bin-dir 可以重复指定，按顺序查找程序，默认是 ./bin
+    let mut bin_dirs = arg_values(&args, "--bin-dir");
+    if bin_dirs.is_empty() {
+        bin_dirs.push("./bin".to_string());
+    }
+    // --interval 指定两次检查之间间隔的秒数，可以是小数，例如 0.5，最小 0.1 秒，默认 15 秒
+    let interval = arg_interval(&args)?;
+    // --stagger 指定同一轮里面依次启动程序之间间隔的秒数，默认 0 表示同时启动
+    let stagger = arg_duration(&args, "--stagger", true)?.unwrap_or(std::time::Duration::ZERO);
+    // --shutdown-timeout 指定关闭命令最多执行的秒数，需要小于服务管理器强制结束进程的时间
+    let shutdown_timeout =
+        arg_duration(&args, "--shutdown-timeout", false)?.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT);
+
+    // --check-config 只检查配置，不启动任何进程，有无法运行的条目时返回非 0
+    if args.iter().any(|arg| arg == "--check-config") {
+        if !check_config(&config, &bin_dirs)? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     wei_env::bin_init("wei-daemon");
     let instance = wei_single::SingleInstance::new("wei-daemon")?;
     if !instance.is_single() { 
@@ -31,14 +60,146 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     //     fs::copy(src, "wei-updater.exe")?;
     // }
 
+    // 进入守护循环之前先确认配置文件可以读取，路径写错时直接报错退出
+    // 标准输入只能读取一次，在执行 --on-startup 之前读取，之后每次循环都复用这份内容
+    let stdin_content = if config == "-" {
+        Some(read_config(std::io::stdin().lock())?)
+    } else {
+        read_config_file(&config)?;
+        None
+    };
+
+    // --on-startup 在启动任何进程之前执行，例如挂载磁盘、预热缓存，失败则不启动守护进程
+    if let Some(command) = arg_value(&args, "--on-startup") {
+        info!("run startup command: {}", command);
+        println!("run startup command: {}", command);
+        if !run_hook(&command, None)? {
+            info!("startup command failed, daemon not started: {}", command);
+            return Err(format!("startup command failed: {}", command).into());
+        }
+    }
+
+    // 收到 SIGTERM/SIGINT/SIGHUP 时只设置退出标记，守护循环在下一次检查时正常退出
+    let shutdown = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    #[cfg(unix)]
+    for signal in [
+        signal_hook::consts::SIGTERM,
+        signal_hook::consts::SIGINT,
+        signal_hook::consts::SIGHUP,
+    ] {
+        signal_hook::flag::register(signal, shutdown.clone())?;
+    }
+
     info!("start daemon");
     println!("start daemon");
-    start().await?;
+    let result = start(&config, stdin_content.as_deref(), &bin_dirs, interval, stagger, &shutdown);
 
-    Ok(())
+    // --on-shutdown 在守护循环退出后执行一次，读取配置出错退出时也会执行，例如把本机从负载均衡上摘除
+    if let Some(command) = arg_value(&args, "--on-shutdown") {
+        info!("run shutdown command: {}", command);
+        println!("run shutdown command: {}", command);
+        match run_hook(&command, Some(shutdown_timeout)) {
+            Ok(true) => {}
+            Ok(false) => {
+                info!("shutdown command failed: {}", command);
+                println!("shutdown command failed: {}", command);
+            }
+            Err(err) => {
+                info!("shutdown command error: {}: {}", command, err);
+                println!("shutdown command error: {}: {}", command, err);
+            }
+        }
+    }
+
+    result
+}
+
+// 默认两次检查之间的间隔
+const DEFAULT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
+// 两次检查之间的最小间隔，每轮都要执行一次 ps 或 tasklist，间隔太小会一直占用 CPU
+const MIN_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+// 默认关闭命令最多执行的时间，超时后结束命令，不阻塞守护进程退出
+const DEFAULT_SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+// 读取命令行参数 name 后面紧跟的值
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+        .cloned()
+}
+
+// 读取以秒为单位的时长参数，可以是小数，allow_zero 为 false 时必须大于 0，超出 Duration 范围的值也返回错误
+fn arg_duration(args: &[String], name: &str, allow_zero: bool) -> Result<Option<std::time::Duration>, String> {
+    let value = match arg_value(args, name) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && (*seconds > 0.0 || (allow_zero && *seconds == 0.0)))
+        .and_then(|seconds| std::time::Duration::try_from_secs_f64(seconds).ok())
+        .map(Some)
+        .ok_or_else(|| format!("invalid {}: {}", name, value))
+}
+
+// 读取 --interval，没有指定时使用默认间隔，小于 MIN_INTERVAL 时返回错误
+fn arg_interval(args: &[String]) -> Result<std::time::Duration, String> {
+    let interval = arg_duration(args, "--interval", false)?.unwrap_or(DEFAULT_INTERVAL);
+    if interval < MIN_INTERVAL {
+        return Err(format!(
+            "invalid --interval: {}s (minimum {}s)",
+            interval.as_secs_f64(),
+            MIN_INTERVAL.as_secs_f64()
+        ));
+    }
+    Ok(interval)
+}
+
+// 读取命令行参数 name 后面紧跟的所有值，参数可以重复出现
+fn arg_values(args: &[String], name: &str) -> Vec<String> {
+    args.windows(2)
+        .filter(|pair| pair[0] == name)
+        .map(|pair| pair[1].clone())
+        .collect()
+}
+
+// 输出版本号、git 提交号、目标平台和编译进来的 features
+// 目前没有定义任何可选 feature，features 为空
+fn print_version(json: bool) {
+    let version = env!("CARGO_PKG_VERSION");
+    let commit = env!("WEI_DAEMON_GIT_COMMIT");
+    let target = env!("WEI_DAEMON_TARGET");
+    let features: Vec<&str> = vec![];
+
+    if json {
+        let features = features.iter()
+            .map(|feature| format!("\"{}\"", feature))
+            .collect::<Vec<String>>()
+            .join(",");
+        println!(
+            "{{\"name\":\"wei-daemon\",\"version\":\"{}\",\"commit\":\"{}\",\"target\":\"{}\",\"features\":[{}]}}",
+            version, commit, target, features
+        );
+    } else {
+        println!("wei-daemon {}", version);
+        println!("commit: {}", commit);
+        println!("target: {}", target);
+        if features.is_empty() {
+            println!("features: none");
+        } else {
+            println!("features: {}", features.join(", "));
+        }
+    }
 }
 
 // 扫描daemon.dat文件
+// daemon.dat 是唯一的配置来源，格式为每行一个程序名或程序路径
+// 以前 lib.rs 里面按 yaml mapping 读取的写法已经删除，
+// 迁移时把 mapping 里面的每个 key 单独写成一行即可，例如 `wei-server: ~` 改成 `wei-server`
 // 使用线程执行check_and_start，保证daemon.dat里面命令要被运行
 // 像wei-task这类型的程序需要在循环里面配置退出程序
 
@@ -49,50 +210,656 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 // 先检查进程是否存在
 // 如果进程不存在就开启进程
 
-pub async fn start() -> Result<(), Box<dyn std::error::Error>> {
+pub fn start(
+    config: &str,
+    stdin_content: Option<&str>,
+    bin_dirs: &[String],
+    interval: std::time::Duration,
+    stagger: std::time::Duration,
+    shutdown: &std::sync::atomic::AtomicBool,
+) -> Result<(), Box<dyn std::error::Error>> {
     loop {
         println!("status: {}", wei_env::status());
         if wei_env::status() == "0" {
             return Ok(());
         }
 
+        if shutdown.load(std::sync::atomic::Ordering::SeqCst) {
+            info!("shutdown signal received");
+            println!("shutdown signal received");
+            return Ok(());
+        }
+
         println!("start check_and_start");
 
-        let content = std::fs::read_to_string("./daemon.dat").unwrap();
+        let content = match stdin_content {
+            Some(content) => content.to_string(),
+            None => read_config_file(config)?,
+        };
+
+        let processes = process_list();
+        let mut not_running = Vec::new();
+        // 同一个程序在 daemon.dat 里面写了多次时，每轮只检查和启动一次
+        let mut checked = std::collections::HashSet::new();
 
         // content内容是每行一个进程名
         for line in content.lines() {
-            let line = line.to_owned();
-            tokio::spawn(async move {
-                let name = line.trim();
-                info!("check {}", name);
-                println!("check {}", name);
-
-                if !is_process_running(&name) {
-                    info!("{} is not running", name);
-                    println!("{} is not running", name);
-                    
-                    wei_run::run(name, vec![]).unwrap();
+            // 跳过空行，否则空的程序名每轮都会被当成没有运行而去启动
+            if line.trim().is_empty() {
+                continue;
+            }
+            // 程序名或路径里面可以使用 %VAR% 或者 ${VAR} 形式的环境变量
+            let name = match expand_env(line.trim()) {
+                Ok(name) => name,
+                Err(err) => {
+                    info!("skip {}: {}", line.trim(), err);
+                    println!("skip {}: {}", line.trim(), err);
+                    continue;
                 }
+            };
+            if !checked.insert(name.clone()) {
+                continue;
+            }
+            info!("check {}", name);
+            println!("check {}", name);
+
+            if is_process_running(&processes, &name) {
+                continue;
+            }
+
+            info!("{} is not running", name);
+            println!("{} is not running", name);
+            not_running.push(name);
+        }
+
+        launch_staggered(not_running, stagger, shutdown, |name| {
+            // wei_run 会一直等到程序退出，所以每个程序都放在独立线程里运行
+            let bin_dirs = bin_dirs.to_vec();
+            std::thread::spawn(move || {
+                run(&name, &bin_dirs).unwrap();
             });
+        });
+
+        wait(interval, shutdown);
+    }
+}
+
+// 同时启动太多程序会抢占 CPU 和磁盘，每启动一个之后等待 stagger，收到退出信号时不再继续启动
+fn launch_staggered<F: FnMut(String)>(
+    names: Vec<String>,
+    stagger: std::time::Duration,
+    shutdown: &std::sync::atomic::AtomicBool,
+    mut launch: F,
+) {
+    for (index, name) in names.into_iter().enumerate() {
+        if index > 0 && !stagger.is_zero() {
+            wait(stagger, shutdown);
+            if shutdown.load(std::sync::atomic::Ordering::SeqCst) {
+                break;
+            }
         }
+        launch(name);
+    }
+}
+
+// 等待下一轮检查，收到退出信号时提前结束等待
+fn wait(interval: std::time::Duration, shutdown: &std::sync::atomic::AtomicBool) {
+    let start = std::time::Instant::now();
+    while !shutdown.load(std::sync::atomic::Ordering::SeqCst) {
+        let remaining = interval.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            break;
+        }
+        std::thread::sleep(remaining.min(std::time::Duration::from_millis(100)));
+    }
+}
+
+// 展开 %VAR% 和 ${VAR} 形式的环境变量，变量不存在时返回错误
+// 空的 %% 和 ${} 按原样保留，没有闭合的 % 或 ${ 也按原样保留，并继续展开后面的内容
+fn expand_env(value: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = value;
+
+    loop {
+        let (start, open, close) = match (rest.find('%'), rest.find("${")) {
+            (Some(percent), Some(dollar)) if dollar < percent => (dollar, "${", '}'),
+            (Some(percent), _) => (percent, "%", '%'),
+            (None, Some(dollar)) => (dollar, "${", '}'),
+            (None, None) => break,
+        };
+
+        let after = &rest[start + open.len()..];
+        let end = match after.find(close) {
+            Some(end) => end,
+            None => {
+                result.push_str(&rest[..start + open.len()]);
+                rest = after;
+                continue;
+            }
+        };
+
+        result.push_str(&rest[..start]);
+        let key = &after[..end];
+        if key.is_empty() {
+            result.push_str(&rest[start..start + open.len() + end + 1]);
+        } else {
+            let value = std::env::var(key)
+                .map_err(|_| format!("environment variable {} is not set", key))?;
+            result.push_str(&value);
+        }
+        rest = &after[end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
 
-        tokio::time::sleep(tokio::time::Duration::from_secs(15)).await;
+// 先按顺序去 bin 目录下面找对应的程序，找到就直接运行
+// 如果没有，则交给 wei_run::run 去当前目录和 wei_env::dir_bin 里面找对应执行的路径
+fn run(name: &str, bin_dirs: &[String]) -> Result<String, Box<dyn std::error::Error>> {
+    // 绝对路径（例如展开 %USERPROFILE% 之后的路径）直接运行，wei_run::run 会在前面加上 ./
+    if std::path::Path::new(name).is_absolute() {
+        info!("run: {:?}", name);
+        return wei_run::command(name, vec![]);
+    }
+
+    match find_in_bin_dirs(name, bin_dirs) {
+        Some(path) => {
+            let path = path.display().to_string();
+            info!("run: {:?}", path);
+            wei_run::command(&path, vec![])
+        }
+        None => wei_run::run(name, vec![]),
     }
 }
 
+// 只查找不带目录的程序名，带路径的配置按原样交给 wei_run
+fn find_in_bin_dirs(name: &str, bin_dirs: &[String]) -> Option<std::path::PathBuf> {
+    if name.is_empty() || name.contains('/') || name.contains('\\') {
+        return None;
+    }
+
+    for dir in bin_dirs {
+        let path = std::path::Path::new(dir).join(name);
+        if path.is_file() {
+            return Some(path);
+        }
+
+        #[cfg(target_os = "windows")]
+        {
+            let path = std::path::Path::new(dir).join(format!("{}.exe", name));
+            if path.is_file() {
+                return Some(path);
+            }
+        }
+    }
+
+    None
+}
+
+// 通过系统 shell 执行钩子命令，并把命令输出写入日志
+// 输出先写到临时文件，命令退出或者超时后再读取，命令留在后台的子进程不会阻塞守护进程
+// 设置了 timeout 时，超时后结束命令并返回错误，命令退出后返回是否执行成功
+fn run_hook(command: &str, timeout: Option<std::time::Duration>) -> Result<bool, Box<dyn std::error::Error>> {
+    static HOOK_COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let output_path = std::env::temp_dir().join(format!(
+        "wei-daemon-hook-{}-{}.log",
+        std::process::id(),
+        HOOK_COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+    ));
+    let output = std::fs::File::create(&output_path)?;
+
+    #[cfg(target_os = "windows")]
+    let mut cmd = {
+        use std::os::windows::process::CommandExt;
+        let mut cmd = std::process::Command::new("cmd");
+        cmd.arg("/C")
+            .arg(command)
+            .creation_flags(winapi::um::winbase::CREATE_NO_WINDOW);
+        cmd
+    };
+
+    // 钩子命令放在单独的进程组里，超时后连同它启动的子进程一起结束
+    #[cfg(not(target_os = "windows"))]
+    let mut cmd = {
+        use std::os::unix::process::CommandExt;
+        let mut cmd = std::process::Command::new("sh");
+        cmd.arg("-c").arg(command).process_group(0);
+        cmd
+    };
+
+    // 钩子命令不读取标准输入，避免 --config - 时把管道里的配置读走
+    let child = output.try_clone().and_then(|stdout| {
+        cmd.stdin(std::process::Stdio::null())
+            .stdout(stdout)
+            .stderr(output)
+            .spawn()
+    });
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => {
+            let _ = std::fs::remove_file(&output_path);
+            return Err(err.into());
+        }
+    };
+
+    let start = std::time::Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+            kill_hook(&mut child)?;
+            break None;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    };
+
+    // 超时结束的命令也把已经输出的内容写入日志
+    if let Ok(output) = std::fs::read(&output_path) {
+        for line in String::from_utf8_lossy(&output).lines() {
+            info!("{}", line);
+            println!("{}", line);
+        }
+    }
+    let _ = std::fs::remove_file(&output_path);
+
+    match status {
+        Some(status) => Ok(status.success()),
+        None => Err(format!("timed out after {}s", start.elapsed().as_secs()).into()),
+    }
+}
+
+// 结束超时的钩子命令和它启动的所有子进程，只结束 sh 或 cmd 的话，子进程会在守护进程退出后继续运行
+fn kill_hook(child: &mut std::process::Child) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(target_os = "windows")]
+    let killed = {
+        use std::os::windows::process::CommandExt;
+        std::process::Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &child.id().to_string()])
+            .creation_flags(winapi::um::winbase::CREATE_NO_WINDOW)
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false)
+    };
+
+    // 进程组号就是 sh 的进程号，向负的进程组号发送信号会结束组里的所有进程
+    #[cfg(not(target_os = "windows"))]
+    let killed = std::process::Command::new("kill")
+        .args(["-KILL", "--", &format!("-{}", child.id())])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false);
+
+    if !killed {
+        child.kill()?;
+    }
+    child.wait()?;
+    Ok(())
+}
+
+// 逐条展开环境变量并查找程序，输出每一条的检查结果，全部可以运行时返回 true
+fn check_config(config: &str, bin_dirs: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+    let content = if config == "-" {
+        read_config(std::io::stdin().lock())?
+    } else {
+        read_config_file(config)?
+    };
+
+    let mut valid = true;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        match expand_env(line).and_then(|name| {
+            resolve(&name, bin_dirs).ok_or_else(|| format!("{} not found", name))
+        }) {
+            Ok(path) => println!("ok    {} -> {}", line, path),
+            Err(err) => {
+                println!("error {}: {}", line, err);
+                valid = false;
+            }
+        }
+    }
+
+    Ok(valid)
+}
+
+// 按 run 的查找顺序找到程序实际运行的路径：绝对路径、bin 目录、当前目录、wei_env::dir_bin
+fn resolve(name: &str, bin_dirs: &[String]) -> Option<String> {
+    let exists = |path: &str| {
+        std::path::Path::new(path).is_file()
+            || (cfg!(target_os = "windows") && std::path::Path::new(&format!("{}.exe", path)).is_file())
+    };
+
+    if std::path::Path::new(name).is_absolute() {
+        return exists(name).then(|| name.to_string());
+    }
+
+    if let Some(path) = find_in_bin_dirs(name, bin_dirs) {
+        return Some(path.display().to_string());
+    }
+
+    let local = format!("./{}", name);
+    if exists(&local) {
+        return Some(local);
+    }
+
+    // wei_env::dir_bin 和 wei_env::read 会创建 ~/.wei 和 bin.dat，只检查配置时不写入用户目录
+    let bin_dat = if cfg!(target_os = "windows") {
+        format!("{}/AppData/Local/wei/bin.dat", std::env::var("USERPROFILE").ok()?)
+    } else {
+        format!("{}/.wei/bin.dat", std::env::var("HOME").ok()?)
+    };
+    if !std::path::Path::new(&bin_dat).is_file() {
+        return None;
+    }
+    wei_env::read(&bin_dat, name)
+        .ok()
+        .filter(|path| exists(path))
+}
+
+// 读取配置文件，错误信息里面带上文件路径
+fn read_config_file(config: &str) -> Result<String, Box<dyn std::error::Error>> {
+    std::fs::read_to_string(config).map_err(|err| format!("{}: {}", config, err).into())
+}
+
+// 从任意 reader 读取 daemon.dat 格式的内容
+fn read_config<R: std::io::Read>(mut reader: R) -> Result<String, std::io::Error> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+// 每轮检查只获取一次进程列表，所有条目共用这份快照，避免每个条目都扫描一遍全部进程
 #[cfg(not(target_os = "windows"))]
-fn is_process_running(name: &str) -> bool {
+fn process_list() -> String {
+    // 只输出进程号和程序名两列，比 ps aux 输出的内容少
     let output = std::process::Command::new("ps")
-        .arg("aux")
+        .args(["-A", "-o", "pid=", "-o", "comm="])
         .output()
         .expect("failed to execute process");
-    let output = String::from_utf8_lossy(&output.stdout);
-    let lines: Vec<&str> = output.split("\n").collect();
-    for line in lines {
-        if line.contains(name) {
-            return true;
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[cfg(not(target_os = "windows"))]
+fn is_process_running(processes: &str, name: &str) -> bool {
+    ps_contains(processes, name, std::process::id())
+}
+
+// ps 每行是进程号和程序名，按程序的文件名比较，跳过守护进程自己
+// 不按子串匹配命令行，否则 --bin-dir /opt/wei-server-tools 这样的参数会让守护进程把自己当成 wei-server
+// Linux 的程序名最多保留 15 个字符，被截断的程序名按前缀比较
+#[cfg(any(not(target_os = "windows"), test))]
+fn ps_contains(processes: &str, name: &str, own_pid: u32) -> bool {
+    let file_name = |path: &str| {
+        std::path::Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    };
+    let name = match file_name(name) {
+        Some(name) => name,
+        None => return false,
+    };
+
+    processes.lines().any(|line| {
+        let (pid, comm) = match line.trim_start().split_once(' ') {
+            Some((pid, comm)) => (pid, comm.trim()),
+            None => return false,
+        };
+        if pid.parse::<u32>().map_or(true, |pid| pid == own_pid) {
+            return false;
+        }
+        match file_name(comm) {
+            Some(comm) => comm == name || (comm.len() == 15 && name.starts_with(&comm)),
+            None => false,
+        }
+    })
+}
+
+#[cfg(target_os = "windows")]
+fn process_list() -> String {
+    use std::os::windows::process::CommandExt;
+
+    let output = std::process::Command::new("tasklist")
+        .args(["/FO", "CSV", "/NH"])
+        .creation_flags(winapi::um::winbase::CREATE_NO_WINDOW)
+        .output()
+        .expect("failed to execute process");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[cfg(target_os = "windows")]
+fn is_process_running(processes: &str, name: &str) -> bool {
+    tasklist_contains(processes, name)
+}
+
+// tasklist 每行第一列是映像名，例如 "wei-server.exe"，去掉结尾的 .exe 后比较，忽略大小写
+// 只去掉 .exe，python3.11 和 wei.server 这样带点的程序名按完整的名字比较
+#[cfg(any(target_os = "windows", test))]
+fn tasklist_contains(processes: &str, name: &str) -> bool {
+    let stem = |path: &str| {
+        std::path::Path::new(path).file_name().map(|name| {
+            let name = name.to_string_lossy().to_lowercase();
+            match name.strip_suffix(".exe") {
+                Some(stem) => stem.to_string(),
+                None => name,
+            }
+        })
+    };
+    let name = match stem(name) {
+        Some(name) => name,
+        None => return false,
+    };
+
+    processes.lines().any(|line| {
+        let image = line.split(',').next().unwrap_or("").trim_matches('"');
+        stem(image).as_deref() == Some(name.as_str())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每个测试使用自己的临时目录，测试结束后删除
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("wei-daemon-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn find_in_bin_dirs_searches_dirs_in_order() {
+        let dir = temp_dir("bin-dirs");
+        let first = dir.join("first");
+        let second = dir.join("second");
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+        std::fs::write(second.join("app"), "").unwrap();
+
+        let bin_dirs = vec![first.display().to_string(), second.display().to_string()];
+        assert_eq!(find_in_bin_dirs("app", &bin_dirs), Some(second.join("app")));
+        assert_eq!(find_in_bin_dirs("missing", &bin_dirs), None);
+        assert_eq!(find_in_bin_dirs("second/app", &[dir.display().to_string()]), None);
+
+        std::fs::write(first.join("app"), "").unwrap();
+        assert_eq!(find_in_bin_dirs("app", &bin_dirs), Some(first.join("app")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tasklist_contains_matches_image_name() {
+        let processes = concat!(
+            "\"System Idle Process\",\"0\",\"Services\",\"0\",\"8 K\"\r\n",
+            "\"Wei-Server.exe\",\"1234\",\"Console\",\"1\",\"10,000 K\"\r\n",
+            "\"wei-ui.exe\",\"5678\",\"Console\",\"1\",\"20,000 K\"\r\n",
+            "\"python3.11.exe\",\"2345\",\"Console\",\"1\",\"30,000 K\"\r\n",
+            "\"wei.exe\",\"3456\",\"Console\",\"1\",\"5,000 K\"\r\n",
+        );
+
+        assert!(tasklist_contains(processes, "wei-server"));
+        assert!(tasklist_contains(processes, "wei-server.exe"));
+        assert!(tasklist_contains(processes, "C:/wei/bin/WEI-UI.exe"));
+        assert!(tasklist_contains(processes, "python3.11"));
+        assert!(tasklist_contains(processes, "PYTHON3.11.EXE"));
+        assert!(!tasklist_contains(processes, "python3"));
+        assert!(tasklist_contains(processes, "wei"));
+        assert!(!tasklist_contains(processes, "wei.server"));
+        assert!(!tasklist_contains(processes, "wei-updater"));
+        assert!(!tasklist_contains(processes, ""));
+    }
+
+    #[test]
+    fn ps_contains_matches_program_name() {
+        let processes = concat!(
+            "    1 init\n",
+            "  100 wei-server\n",
+            "  200 /Applications/Wei UI.app/Contents/MacOS/wei-ui\n",
+            "  300 wei-daemon\n",
+            "  400 wei-server-mana\n",
+        );
+
+        assert!(ps_contains(processes, "wei-server", 300));
+        assert!(ps_contains(processes, "/usr/local/bin/wei-ui", 300));
+        assert!(ps_contains(processes, "wei-server-manager", 300));
+        assert!(!ps_contains(processes, "wei", 300));
+        assert!(!ps_contains(processes, "wei-updater", 300));
+        assert!(!ps_contains(processes, "wei-daemon", 300));
+        assert!(ps_contains(processes, "wei-daemon", 1));
+        assert!(!ps_contains(processes, "", 300));
+    }
+
+    #[test]
+    fn expand_env_expands_both_forms() {
+        std::env::set_var("WEI_DAEMON_TEST_DIR", "/opt/wei");
+        std::env::set_var("WEI_DAEMON_TEST_NAME", "wei-server");
+
+        assert_eq!(expand_env("%WEI_DAEMON_TEST_DIR%/app").unwrap(), "/opt/wei/app");
+        assert_eq!(expand_env("${WEI_DAEMON_TEST_DIR}/app").unwrap(), "/opt/wei/app");
+        assert_eq!(
+            expand_env("%WEI_DAEMON_TEST_DIR%/bin/${WEI_DAEMON_TEST_NAME}").unwrap(),
+            "/opt/wei/bin/wei-server"
+        );
+        assert_eq!(
+            expand_env("${WEI_DAEMON_TEST_DIR}/%WEI_DAEMON_TEST_NAME%.exe").unwrap(),
+            "/opt/wei/wei-server.exe"
+        );
+        assert_eq!(expand_env("wei-server").unwrap(), "wei-server");
+    }
+
+    #[test]
+    fn expand_env_reports_unset_variable() {
+        assert_eq!(
+            expand_env("%WEI_DAEMON_TEST_UNSET%/app"),
+            Err("environment variable WEI_DAEMON_TEST_UNSET is not set".to_string())
+        );
+        assert!(expand_env("${WEI_DAEMON_TEST_UNSET}/app").is_err());
+    }
+
+    #[test]
+    fn expand_env_keeps_empty_and_unclosed_markers() {
+        std::env::set_var("WEI_DAEMON_TEST_HOME", "/home/wei");
+
+        assert_eq!(expand_env("100%%").unwrap(), "100%%");
+        assert_eq!(expand_env("a${}b").unwrap(), "a${}b");
+        assert_eq!(expand_env("a%b${WEI_DAEMON_TEST_HOME}").unwrap(), "a%b/home/wei");
+        assert_eq!(expand_env("${WEI_DAEMON_TEST_HOME}/a${b").unwrap(), "/home/wei/a${b");
+        assert_eq!(expand_env("a${b%WEI_DAEMON_TEST_HOME%").unwrap(), "a${b/home/wei");
+    }
+
+    #[test]
+    fn launch_staggered_waits_between_launches() {
+        let stagger = std::time::Duration::from_millis(200);
+        let shutdown = std::sync::atomic::AtomicBool::new(false);
+        let names = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+
+        let mut launches = Vec::new();
+        launch_staggered(names, stagger, &shutdown, |name| {
+            launches.push((name, std::time::Instant::now()));
+        });
+
+        let names: Vec<&str> = launches.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        for pair in launches.windows(2) {
+            let gap = pair[1].1 - pair[0].1;
+            assert!(gap >= stagger, "gap {:?} shorter than stagger", gap);
+            assert!(gap < stagger * 2, "gap {:?} much longer than stagger", gap);
         }
     }
-    false
-}
\ No newline at end of file
+
+    #[test]
+    fn launch_staggered_stops_on_shutdown() {
+        let stagger = std::time::Duration::from_secs(10);
+        let shutdown = std::sync::atomic::AtomicBool::new(false);
+        let names = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+
+        let start = std::time::Instant::now();
+        let mut launches = Vec::new();
+        launch_staggered(names, stagger, &shutdown, |name| {
+            launches.push(name);
+            shutdown.store(true, std::sync::atomic::Ordering::SeqCst);
+        });
+
+        assert_eq!(launches, ["a"]);
+        assert!(start.elapsed() < stagger);
+    }
+
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("wei-daemon")
+            .chain(list.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn arg_value_reads_following_value() {
+        let args = args(&["--config", "a.dat", "--bin-dir", "one", "--bin-dir", "two", "--interval"]);
+
+        assert_eq!(arg_value(&args, "--config"), Some("a.dat".to_string()));
+        assert_eq!(arg_value(&args, "--bin-dir"), Some("one".to_string()));
+        assert_eq!(arg_value(&args, "--interval"), None);
+        assert_eq!(arg_value(&args, "--stagger"), None);
+        assert_eq!(arg_values(&args, "--bin-dir"), ["one", "two"]);
+        assert!(arg_values(&args, "--interval").is_empty());
+    }
+
+    #[test]
+    fn arg_duration_parses_seconds() {
+        let duration = |value: &str, allow_zero: bool| {
+            arg_duration(&args(&["--stagger", value]), "--stagger", allow_zero)
+        };
+
+        assert_eq!(arg_duration(&args(&[]), "--stagger", true), Ok(None));
+        assert_eq!(duration("0.5", false), Ok(Some(std::time::Duration::from_millis(500))));
+        assert_eq!(duration("0", true), Ok(Some(std::time::Duration::ZERO)));
+        assert_eq!(duration("0", false), Err("invalid --stagger: 0".to_string()));
+        assert_eq!(duration("-1", true), Err("invalid --stagger: -1".to_string()));
+        assert_eq!(duration("NaN", true), Err("invalid --stagger: NaN".to_string()));
+        assert_eq!(duration("inf", true), Err("invalid --stagger: inf".to_string()));
+        assert_eq!(duration("1e30", true), Err("invalid --stagger: 1e30".to_string()));
+        assert_eq!(duration("x", true), Err("invalid --stagger: x".to_string()));
+    }
+
+    #[test]
+    fn arg_interval_enforces_minimum() {
+        assert_eq!(arg_interval(&args(&[])), Ok(DEFAULT_INTERVAL));
+        assert_eq!(
+            arg_interval(&args(&["--interval", "0.5"])),
+            Ok(std::time::Duration::from_millis(500))
+        );
+        assert_eq!(arg_interval(&args(&["--interval", "0.1"])), Ok(MIN_INTERVAL));
+        assert_eq!(
+            arg_interval(&args(&["--interval", "0.05"])),
+            Err("invalid --interval: 0.05s (minimum 0.1s)".to_string())
+        );
+        assert_eq!(
+            arg_interval(&args(&["--interval", "0"])),
+            Err("invalid --interval: 0".to_string())
+        );
+    }
+}